@@ -1,10 +1,16 @@
+#[cfg(not(target_os = "linux"))]
 use async_process::Command;
+#[cfg(not(target_os = "linux"))]
 use futures::future::try_join_all;
+#[cfg(not(target_os = "linux"))]
 use std::env;
-use std::ops::Range;
+#[cfg(not(target_os = "linux"))]
 use std::process;
+#[cfg(not(target_os = "linux"))]
 use std::process::Output;
-use std::{fmt, io};
+use std::fmt;
+#[cfg(not(target_os = "linux"))]
+use std::io;
 
 /// Potential Errors returned from public functions.
 #[derive(Eq, PartialEq, Debug)]
@@ -28,8 +34,10 @@ impl fmt::Display for NotHybridCPU {
     }
 }
 
+#[cfg(not(target_os = "linux"))]
 #[derive(Debug, Clone)]
 struct UnknownCoreType;
+#[cfg(not(target_os = "linux"))]
 impl fmt::Display for UnknownCoreType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Error reading hybrid core type")
@@ -44,40 +52,108 @@ impl fmt::Display for TasksetFailure {
     }
 }
 
-/// Contains the range of indexes for each core type.
+/// Contains the logical CPU indexes belonging to each core type. Membership
+/// is explicit rather than range-based, since P and E cores are not
+/// guaranteed to occupy a contiguous prefix/suffix of the logical CPU
+/// numbering (SMT siblings can interleave, and the split point varies by
+/// part).
 /// # Example
 /// ```
 /// use e_core_detection::CorePELayout;
-/// let i7_1200k = CorePELayout {
-///     p_cores: (0..15),
-///     e_cores: (16..19),
-/// };
+/// let i7_1200k = CorePELayout::new(vec![0, 1, 2, 3], vec![4, 5]);
 /// ```
 #[derive(Debug)]
 pub struct CorePELayout {
-    pub p_cores: Range<u8>,
-    pub e_cores: Range<u8>,
+    pub p_cores: Vec<u8>,
+    pub e_cores: Vec<u8>,
+    /// CPUs that were actually classified into `p_cores` or `e_cores`, either
+    /// by CPUID probing or from the kernel's hybrid PMU cpumasks. Lets callers
+    /// tell which CPUs this layout has an opinion about, since CPUs outside
+    /// the process's allowed set (cgroup `cpuset`, `taskset`, kernel-isolated
+    /// CPUs) are never included.
+    pub probed_cpus: Vec<u8>,
 }
 
 impl CorePELayout {
-    /// Create the p & e core ranges partitioned at the index of the last p-core.
+    /// Build a layout from the explicit set of CPUs detected for each core type.
     /// # Arguments
-    /// * `p_core_end_idx`: The index of the last p core.
+    /// * `p_cores`: Logical CPU indexes classified as P-cores.
+    /// * `e_cores`: Logical CPU indexes classified as E-cores.
     /// returns: CorePELayout
     /// # Example
     /// ```
     /// use e_core_detection::CorePELayout;
-    /// let i7_12700k = CorePELayout::new(15);
+    /// let i7_12700k = CorePELayout::new(vec![0, 1, 2, 3], vec![4, 5]);
     /// ```
-    pub fn new(p_core_end_idx: u8) -> CorePELayout {
+    pub fn new(p_cores: Vec<u8>, e_cores: Vec<u8>) -> CorePELayout {
+        let mut probed_cpus: Vec<u8> = p_cores.iter().chain(e_cores.iter()).copied().collect();
+        probed_cpus.sort_unstable();
+
         CorePELayout {
-            p_cores: (0..p_core_end_idx),
-            e_cores: (p_core_end_idx + 1..19),
+            p_cores,
+            e_cores,
+            probed_cpus,
         }
     }
     /// Simple format string. Used for output in binary.
     pub fn formatted_string(&self) -> String {
-        format!("P CORES: {:?}\nE Cores: {:?}", self.p_cores, self.e_cores)
+        format!(
+            "P CORES: {}\nE Cores: {}",
+            format_as_compact_ranges(&self.p_cores),
+            format_as_compact_ranges(&self.e_cores)
+        )
+    }
+
+    /// Pin the calling thread to every CPU of the given type in this layout,
+    /// e.g. `layout.set_affinity_current(CoreType::E)` to move a background
+    /// batch job off the performance cores. Returns the CPUs actually applied.
+    pub fn set_affinity_current(&self, core_type: CoreType) -> Vec<u8> {
+        set_affinity_for_current(self.cpus_of(core_type))
+    }
+
+    /// Pin every thread of the calling process to every CPU of the given
+    /// type. Returns the CPUs actually applied.
+    pub fn set_affinity_process(&self, core_type: CoreType) -> Vec<u8> {
+        set_affinity_for_process(self.cpus_of(core_type))
+    }
+
+    fn cpus_of(&self, core_type: CoreType) -> &[u8] {
+        match core_type {
+            CoreType::P => &self.p_cores,
+            CoreType::E => &self.e_cores,
+        }
+    }
+}
+
+/// Render a set of CPU indexes as compact ranges, e.g. `[0-3,7]`.
+fn format_as_compact_ranges(cpus: &[u8]) -> String {
+    let mut sorted = cpus.to_vec();
+    sorted.sort_unstable();
+
+    let mut parts = Vec::new();
+    let mut iter = sorted.into_iter();
+    if let Some(mut start) = iter.next() {
+        let mut end = start;
+        for cpu in iter {
+            if cpu == end + 1 {
+                end = cpu;
+                continue;
+            }
+            parts.push(format_range_part(start, end));
+            start = cpu;
+            end = cpu;
+        }
+        parts.push(format_range_part(start, end));
+    }
+
+    format!("[{}]", parts.join(","))
+}
+
+fn format_range_part(start: u8, end: u8) -> String {
+    if start == end {
+        start.to_string()
+    } else {
+        format!("{}-{}", start, end)
     }
 }
 
@@ -87,7 +163,12 @@ pub async fn get_pe_partition_async() -> Result<CorePELayout, CoreTypeFetchError
         return Err(CoreTypeFetchError::NotHybridCPU);
     }
 
-    taskset_async(num_cpus::get() as u8).await
+    #[cfg(target_os = "linux")]
+    if let Some(layout) = get_pe_partition_sysfs() {
+        return Ok(layout);
+    }
+
+    detect_layout_async(num_cpus::get() as u8).await
 }
 /// Request the CorePELayout for the current system synchronously.
 pub fn get_pe_partition_sync() -> Result<CorePELayout, CoreTypeFetchError> {
@@ -95,7 +176,65 @@ pub fn get_pe_partition_sync() -> Result<CorePELayout, CoreTypeFetchError> {
         return Err(CoreTypeFetchError::NotHybridCPU);
     }
 
-    taskset_sync(num_cpus::get() as u8)
+    #[cfg(target_os = "linux")]
+    if let Some(layout) = get_pe_partition_sysfs() {
+        return Ok(layout);
+    }
+
+    detect_layout_sync(num_cpus::get() as u8)
+}
+
+/// Fast path: on kernels that expose perf's hybrid PMU topology, the P/E
+/// split is already published as CPU-range lists and needs no per-core
+/// probing at all. Falls back (returns `None`) on older, non-hybrid-aware
+/// kernels so callers can drop back to the CPUID-based detection.
+///
+/// The kernel's view covers every CPU it knows about, regardless of whether
+/// this process may run there, so the result is still intersected with
+/// `allowed_cpus` — otherwise a cgroup `cpuset` or `taskset` restriction
+/// would be silently ignored on this path alone.
+#[cfg(target_os = "linux")]
+fn get_pe_partition_sysfs() -> Option<CorePELayout> {
+    let p_cores = read_cpu_list("/sys/devices/cpu_core/cpus")?;
+    let e_cores = read_cpu_list("/sys/devices/cpu_atom/cpus")?;
+
+    let allowed = allowed_cpus(enumerated_cpu_bound());
+    let p_cores = p_cores.into_iter().filter(|cpu| allowed.contains(cpu)).collect();
+    let e_cores = e_cores.into_iter().filter(|cpu| allowed.contains(cpu)).collect();
+
+    Some(CorePELayout::new(p_cores, e_cores))
+}
+
+#[cfg(target_os = "linux")]
+fn read_cpu_list(path: &str) -> Option<Vec<u8>> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    parse_cpu_range_list(contents.trim())
+}
+
+/// Parse a comma-separated CPU-range list (e.g. `0-15` or `16-23,30`) as
+/// used throughout `/sys/devices/system/cpu` and the hybrid PMU cpumasks,
+/// expanding each range into the individual CPU indexes it covers.
+#[cfg(target_os = "linux")]
+fn parse_cpu_range_list(input: &str) -> Option<Vec<u8>> {
+    let mut cpus = Vec::new();
+
+    for part in input.split(',') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+
+        match part.split_once('-') {
+            Some((a, b)) => cpus.extend(a.parse::<u8>().ok()?..=b.parse::<u8>().ok()?),
+            None => cpus.push(part.parse::<u8>().ok()?),
+        }
+    }
+
+    if cpus.is_empty() {
+        None
+    } else {
+        Some(cpus)
+    }
 }
 
 /// Determine whether the current core is a P or E core.
@@ -113,13 +252,17 @@ pub unsafe fn test_core() -> String {
     }
 }
 
-#[derive(PartialEq, Debug)]
-enum CoreType {
+/// A core's microarchitecture within a hybrid CPU.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CoreType {
+    /// Performance core (Intel® Core™).
     P,
+    /// Efficiency core (Intel Atom®).
     E,
 }
 
 impl CoreType {
+    #[cfg(not(target_os = "linux"))]
     fn from_string(input: &str) -> Option<CoreType> {
         match input {
             "P_CORE" => Some(CoreType::P),
@@ -127,6 +270,17 @@ impl CoreType {
             _ => None,
         }
     }
+
+    /// Classify CPUID leaf 0x1A's `eax` register: bits 31:24 carry the core
+    /// type (`0x40` = P/Core, `0x20` = E/Atom).
+    #[cfg(target_os = "linux")]
+    fn from_cpuid_eax(eax: u32) -> Option<CoreType> {
+        match eax >> 24 {
+            0x40 => Some(CoreType::P),
+            0x20 => Some(CoreType::E),
+            _ => None,
+        }
+    }
 }
 
 fn is_hybrid() -> bool {
@@ -136,7 +290,209 @@ fn is_hybrid() -> bool {
     }
 }
 
-async fn taskset_async(n_cores: u8) -> Result<CorePELayout, CoreTypeFetchError> {
+/// Async wrapper around `detect_layout_sync`. The work itself is
+/// synchronous and microsecond-scale, so there's nothing to actually await.
+#[cfg(target_os = "linux")]
+fn detect_layout_async(n_cores: u8) -> std::future::Ready<Result<CorePELayout, CoreTypeFetchError>> {
+    std::future::ready(detect_layout_sync(n_cores))
+}
+
+/// In-process hybrid detection: pin a throwaway thread to a single logical
+/// CPU and read its CPUID leaf 0x1A directly, instead of re-exec'ing the
+/// crate's own binary under `taskset`. This is what lets us run as a library
+/// (no reliance on `env::args().next()`) and brings detection down to
+/// microsecond scale.
+#[cfg(target_os = "linux")]
+fn detect_layout_sync(_n_cores: u8) -> Result<CorePELayout, CoreTypeFetchError> {
+    let mut p_cores = Vec::new();
+    let mut e_cores = Vec::new();
+
+    for cpu in allowed_cpus(enumerated_cpu_bound()) {
+        match detect_core_type_pinned(cpu) {
+            Some(CoreType::P) => p_cores.push(cpu),
+            Some(CoreType::E) => e_cores.push(cpu),
+            // CPUs that fail to pin (offline/forbidden) or report an
+            // unrecognised core type are simply skipped.
+            None => {}
+        }
+    }
+
+    if p_cores.is_empty() && e_cores.is_empty() {
+        return Err(CoreTypeFetchError::UnknownCoreType);
+    }
+
+    Ok(CorePELayout::new(p_cores, e_cores))
+}
+
+/// Exclusive upper bound on logical CPU indexes worth scanning, derived from
+/// the kernel's enumeration of possible CPUs. `num_cpus::get()` is *not*
+/// usable here: on Linux it honors `sched_getaffinity` and reports the
+/// allowed *count*, not the highest possible index, so under `taskset -c
+/// 16-19` it returns 4 and a `0..4` scan never reaches the allowed,
+/// high-numbered CPUs at all.
+#[cfg(target_os = "linux")]
+fn enumerated_cpu_bound() -> u8 {
+    read_cpu_list("/sys/devices/system/cpu/possible")
+        .or_else(|| read_cpu_list("/sys/devices/system/cpu/present"))
+        .and_then(|cpus| cpus.into_iter().max())
+        .map(|max| max.saturating_add(1))
+        .unwrap_or(u8::MAX)
+}
+
+/// The logical CPUs worth probing: those this process is actually allowed to
+/// run on (per `sched_getaffinity`), minus any the kernel has set aside as
+/// isolated. Probing outside this set is pointless under a cgroup `cpuset`,
+/// `taskset`, or `isolcpus=`/`nohz_full` deployment — pinning either fails
+/// outright or succeeds on a CPU this process will never actually be
+/// scheduled on.
+#[cfg(target_os = "linux")]
+fn allowed_cpus(cpu_bound: u8) -> Vec<u8> {
+    let mut affinity: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let scheduler_allowed: Vec<u8> = unsafe {
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut affinity) == 0 {
+            (0..cpu_bound)
+                .filter(|&cpu| libc::CPU_ISSET(cpu as usize, &affinity))
+                .collect()
+        } else {
+            (0..cpu_bound).collect()
+        }
+    };
+
+    let isolated = read_isolated_cpus();
+    scheduler_allowed
+        .into_iter()
+        .filter(|cpu| !isolated.contains(cpu))
+        .collect()
+}
+
+/// Parse the kernel's isolated-CPU list (`/sys/devices/system/cpu/isolated`),
+/// same range-list format as the hybrid PMU cpumasks. Empty if the file is
+/// absent or no CPUs are isolated.
+#[cfg(target_os = "linux")]
+fn read_isolated_cpus() -> Vec<u8> {
+    read_cpu_list("/sys/devices/system/cpu/isolated").unwrap_or_default()
+}
+
+/// Spawn a thread, pin it to `cpu` via `sched_setaffinity`, and classify the
+/// core it lands on from CPUID leaf 0x1A. Returns `None` if the CPU could not
+/// be pinned (e.g. it's offline) or if the core type was unrecognised.
+///
+/// The affinity change only ever applies to the freshly spawned thread, so
+/// the calling thread's own affinity is untouched.
+#[cfg(target_os = "linux")]
+fn detect_core_type_pinned(cpu: u8) -> Option<CoreType> {
+    std::thread::spawn(move || {
+        if !pin_current_thread_to(cpu) {
+            return None;
+        }
+
+        let res = core::arch::x86_64::__cpuid_count(0x1A, 0x00);
+        CoreType::from_cpuid_eax(res.eax)
+    })
+    .join()
+    .unwrap_or(None)
+}
+
+/// Pin the calling thread to a single logical CPU. Returns `false` if the
+/// CPU is not schedulable for this thread (e.g. offline or outside the
+/// process's allowed set).
+#[cfg(target_os = "linux")]
+fn pin_current_thread_to(cpu: u8) -> bool {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(cpu as usize, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn cpu_set_of(core_ids: &[u8]) -> libc::cpu_set_t {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &cpu in core_ids {
+            libc::CPU_SET(cpu as usize, &mut set);
+        }
+        set
+    }
+}
+
+/// Pin the calling thread to exactly `core_ids`, mirroring
+/// `core_affinity::set_for_current`. Returns the CPUs actually applied:
+/// either all of `core_ids`, or empty if the affinity syscall failed.
+pub fn set_affinity_for_current(core_ids: &[u8]) -> Vec<u8> {
+    #[cfg(target_os = "linux")]
+    {
+        let set = cpu_set_of(core_ids);
+        let applied =
+            unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0 };
+        if applied {
+            core_ids.to_vec()
+        } else {
+            Vec::new()
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = core_ids;
+        Vec::new()
+    }
+}
+
+/// Pin every thread of the calling process to exactly `core_ids`. Returns
+/// the CPUs actually applied: all of `core_ids` if every thread found under
+/// `/proc/self/task` accepted the change, empty otherwise. A thread that
+/// exits between being listed and being pinned (`ESRCH`) is not treated as
+/// a failure — it's an expected race, not a sign the affinity change itself
+/// is wrong.
+pub fn set_affinity_for_process(core_ids: &[u8]) -> Vec<u8> {
+    #[cfg(target_os = "linux")]
+    {
+        let set = cpu_set_of(core_ids);
+        let Ok(tasks) = std::fs::read_dir("/proc/self/task") else {
+            return Vec::new();
+        };
+
+        let mut any_applied = false;
+        let mut any_failed = false;
+        for task in tasks.flatten() {
+            let Some(tid) = task
+                .file_name()
+                .to_str()
+                .and_then(|s| s.parse::<libc::pid_t>().ok())
+            else {
+                continue;
+            };
+
+            let ret = unsafe {
+                libc::sched_setaffinity(tid, std::mem::size_of::<libc::cpu_set_t>(), &set)
+            };
+            match ret {
+                0 => any_applied = true,
+                _ if std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH) => {}
+                _ => any_failed = true,
+            }
+        }
+
+        if any_applied && !any_failed {
+            core_ids.to_vec()
+        } else {
+            Vec::new()
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = core_ids;
+        Vec::new()
+    }
+}
+
+/// Fallback detection path for non-Linux targets: re-exec the crate's own
+/// binary under `taskset` on every logical CPU and read back its reported
+/// core type. Kept only where `sched_setaffinity` isn't available.
+#[cfg(not(target_os = "linux"))]
+async fn detect_layout_async(n_cores: u8) -> Result<CorePELayout, CoreTypeFetchError> {
     let promises: Vec<_> = (0..n_cores)
         .into_iter()
         .map(|i| {
@@ -158,7 +514,8 @@ async fn taskset_async(n_cores: u8) -> Result<CorePELayout, CoreTypeFetchError>
     process_raw_command_out(completed).map_err(|_| CoreTypeFetchError::UnknownCoreType)
 }
 
-fn taskset_sync(n_cores: u8) -> Result<CorePELayout, CoreTypeFetchError> {
+#[cfg(not(target_os = "linux"))]
+fn detect_layout_sync(n_cores: u8) -> Result<CorePELayout, CoreTypeFetchError> {
     let cores_raw_strs: Vec<_> = (0..n_cores)
         .map(|i| {
             process::Command::new("taskset")
@@ -173,25 +530,29 @@ fn taskset_sync(n_cores: u8) -> Result<CorePELayout, CoreTypeFetchError> {
     process_raw_command_out(cores_raw_strs).map_err(|_| CoreTypeFetchError::UnknownCoreType)
 }
 
+#[cfg(not(target_os = "linux"))]
 fn process_raw_command_out(
     raw_out: Vec<io::Result<Output>>,
 ) -> Result<CorePELayout, UnknownCoreType> {
-    let p_and_e: (Vec<_>, Vec<_>) = raw_out
-        .into_iter()
-        .map(|core_type| {
-            match core_type {
-                Ok(c) => {
-                    let res = std::str::from_utf8(&*c.stdout)
-                        .map_err(|_| CoreTypeFetchError::TasksetFailure)?;
-                    CoreType::from_string(res.trim())
-                }
-                Err(_) => None,
-            }
-            .ok_or(CoreTypeFetchError::UnknownCoreType)
-        })
-        .partition(|ct| *ct == Ok(CoreType::P));
+    let mut p_cores = Vec::new();
+    let mut e_cores = Vec::new();
+
+    for (cpu, core_type) in raw_out.into_iter().enumerate() {
+        let core_type = match core_type {
+            Ok(c) => std::str::from_utf8(&c.stdout)
+                .ok()
+                .and_then(|s| CoreType::from_string(s.trim())),
+            Err(_) => None,
+        };
+
+        match core_type {
+            Some(CoreType::P) => p_cores.push(cpu as u8),
+            Some(CoreType::E) => e_cores.push(cpu as u8),
+            None => return Err(UnknownCoreType),
+        }
+    }
 
-    Ok(CorePELayout::new(p_and_e.0.len() as u8 - 1))
+    Ok(CorePELayout::new(p_cores, e_cores))
 }
 
 #[cfg(test)]